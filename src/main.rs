@@ -1,23 +1,207 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use rustyline::{Editor, error::ReadlineError, Config as RlCfg};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use rustyline::{Cmd, ConditionalEventHandler, Editor, Event, EventContext, EventHandler, KeyCode, KeyEvent, Modifiers, Movement, RepeatCount, error::ReadlineError, Config as RlCfg};
 use rustyline::completion::{Completer, Pair};
 use rustyline::highlight::Highlighter;
 use rustyline::hint::Hinter;
 use rustyline::Helper;
+use rustyline::history::History;
 use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use serde::{Serialize, Deserialize};
 use chrono::Local;
 use whoami;
 
+/// Outcome of an interactive Ctrl-R history search.
+#[derive(Debug, Clone, PartialEq)]
+enum SelectionResult {
+    /// A match was found, inserted into the line, and the search closed.
+    Selected(String),
+    /// A match was inserted but the search stays open for further refinement.
+    Edit(String),
+    /// Enter or Tab was pressed while no history entry matched the query.
+    Cancelled,
+}
+
+/// Score `candidate` as a fuzzy subsequence match of `query`: every query
+/// char must appear in order. Consecutive matches and matches right after a
+/// separator (`/`, `_`, `-`, ` `) score higher. `None` if `query` isn't a
+/// subsequence of `candidate`.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let q: Vec<char> = query.to_lowercase().chars().collect();
+    let c: Vec<char> = candidate.to_lowercase().chars().collect();
+
+    let mut score = 0i64;
+    let mut qi = 0;
+    let mut prev_match: Option<usize> = None;
+
+    for (ci, &ch) in c.iter().enumerate() {
+        if qi >= q.len() {
+            break;
+        }
+        if ch == q[qi] {
+            score += 1;
+            if prev_match == Some(ci.wrapping_sub(1)) {
+                score += 5;
+            }
+            if ci > 0 && matches!(c[ci - 1], '/' | '_' | '-' | ' ') {
+                score += 3;
+            }
+            prev_match = Some(ci);
+            qi += 1;
+        }
+    }
+
+    if qi == q.len() {
+        Some(score)
+    } else {
+        None
+    }
+}
+
+/// Rank every history entry against `query`, most recent first among ties,
+/// best score first overall.
+fn ranked_matches(query: &str, history: &History) -> Vec<String> {
+    let mut scored: Vec<(i64, String)> = (0..history.len())
+        .rev()
+        .filter_map(|i| history.get(i).map(|e| e.to_string()))
+        .filter_map(|entry| fuzzy_score(query, &entry).map(|s| (s, entry)))
+        .collect();
+    scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    scored.dedup_by(|a, b| a.1 == b.1);
+    scored.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Fish-style autosuggestion: the remaining suffix of the most recent
+/// history entry that starts with `line`, or `None` if nothing matches.
+fn history_suffix(line: &str, history: &History) -> Option<String> {
+    (0..history.len())
+        .rev()
+        .filter_map(|i| history.get(i))
+        .find(|entry| entry.starts_with(line) && entry.len() > line.len())
+        .map(|entry| entry[line.len()..].to_string())
+}
+
+/// Right-arrow at the end of the line accepts the current autosuggestion
+/// instead of just moving the cursor (which would be a no-op there anyway).
+///
+/// `EventContext` has no history access, so rather than recompute the
+/// suggestion here we just read back whatever `Comp::hint` already put on
+/// screen.
+struct AcceptHint {
+    search_mode: Arc<AtomicBool>,
+}
+
+impl ConditionalEventHandler for AcceptHint {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, ctx: &EventContext) -> Option<Cmd> {
+        if self.search_mode.load(Ordering::Relaxed) || ctx.pos() != ctx.line().len() {
+            return None;
+        }
+        ctx.hint_text().map(|suffix| Cmd::Insert(1, suffix.to_string()))
+    }
+}
+
+/// Ctrl-R: flips search mode on/off without touching the line.
+struct ToggleSearch {
+    mode: Arc<AtomicBool>,
+    index: Arc<AtomicUsize>,
+}
+
+impl ConditionalEventHandler for ToggleSearch {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, _ctx: &EventContext) -> Option<Cmd> {
+        let now_on = !self.mode.load(Ordering::Relaxed);
+        self.mode.store(now_on, Ordering::Relaxed);
+        self.index.store(0, Ordering::Relaxed);
+        Some(Cmd::Noop)
+    }
+}
+
+/// Up/Down while searching cycle through ranked matches; otherwise they fall
+/// back to rustyline's normal history browsing.
+struct CycleSearch {
+    mode: Arc<AtomicBool>,
+    index: Arc<AtomicUsize>,
+    up: bool,
+}
+
+impl ConditionalEventHandler for CycleSearch {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, _ctx: &EventContext) -> Option<Cmd> {
+        if !self.mode.load(Ordering::Relaxed) {
+            return None;
+        }
+        let cur = self.index.load(Ordering::Relaxed);
+        self.index.store(if self.up { cur + 1 } else { cur.saturating_sub(1) }, Ordering::Relaxed);
+        Some(Cmd::Noop)
+    }
+}
+
+/// Enter while searching inserts the currently-ranked match and closes the
+/// search; Tab inserts it but leaves the search open for refinement.
+struct AcceptSearch {
+    mode: Arc<AtomicBool>,
+    /// The full candidate `Comp::hint` last computed and is displaying (not
+    /// a prefix/suffix of it — fuzzy matches don't share a prefix with the
+    /// typed query, so there's no "remainder" to slice out of `hint_text()`).
+    search_match: Arc<Mutex<Option<String>>>,
+    keep_open: bool,
+}
+
+impl ConditionalEventHandler for AcceptSearch {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, _ctx: &EventContext) -> Option<Cmd> {
+        if !self.mode.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let cand = self.search_match.lock().unwrap().clone();
+        let result = match cand {
+            Some(cand) if self.keep_open => SelectionResult::Edit(cand),
+            Some(cand) => SelectionResult::Selected(cand),
+            None => SelectionResult::Cancelled,
+        };
+
+        if !self.keep_open {
+            self.mode.store(false, Ordering::Relaxed);
+        }
+
+        match result {
+            SelectionResult::Selected(cand) | SelectionResult::Edit(cand) => {
+                Some(Cmd::Replace(Movement::WholeLine, Some(cand)))
+            }
+            SelectionResult::Cancelled => Some(Cmd::Noop),
+        }
+    }
+}
+
+/// Esc while searching aborts without touching the line.
+struct CancelSearch {
+    mode: Arc<AtomicBool>,
+}
+
+impl ConditionalEventHandler for CancelSearch {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, _ctx: &EventContext) -> Option<Cmd> {
+        if !self.mode.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.mode.store(false, Ordering::Relaxed);
+        Some(Cmd::Noop)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct Cfg {
     ps: String,
     al: HashMap<String, String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
 }
 
 impl Default for Cfg {
@@ -25,11 +209,16 @@ impl Default for Cfg {
         Self {
             ps: "->".to_string(),
             al: HashMap::new(),
+            env: HashMap::new(),
         }
     }
 }
 
-struct Comp;
+struct Comp {
+    search_mode: Arc<AtomicBool>,
+    search_index: Arc<AtomicUsize>,
+    search_match: Arc<Mutex<Option<String>>>,
+}
 
 impl Validator for Comp {
     fn validate(&self, _: &mut ValidationContext) -> Result<ValidationResult, ReadlineError> {
@@ -89,18 +278,217 @@ impl Completer for Comp {
 
 impl Hinter for Comp {
     type Hint = String;
-    fn hint(&self, _: &str, _: usize, _: &rustyline::Context<'_>) -> Option<Self::Hint> {
-        None
+    fn hint(&self, line: &str, pos: usize, ctx: &rustyline::Context<'_>) -> Option<Self::Hint> {
+        if pos != line.len() {
+            return None;
+        }
+        if self.search_mode.load(Ordering::Relaxed) {
+            let matches = ranked_matches(line, ctx.history());
+            let idx = self.search_index.load(Ordering::Relaxed) % matches.len().max(1);
+            let cand = matches.into_iter().nth(idx);
+            *self.search_match.lock().unwrap() = cand.clone();
+            // A fuzzy match has no guaranteed prefix relationship to `line`,
+            // so unlike the suffix-autosuggestion path below we can't just
+            // append the remainder — that would run the candidate straight
+            // into the typed query with no separation. Set it off instead.
+            return cand.map(|c| format!("  -> {}", c));
+        }
+        if line.is_empty() {
+            return None;
+        }
+        history_suffix(line, ctx.history())
     }
 }
 
-impl Highlighter for Comp {}
+impl Highlighter for Comp {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> std::borrow::Cow<'h, str> {
+        std::borrow::Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+}
 impl Helper for Comp {}
 
+/// A single stage of a pipeline: a command plus whatever redirections apply to it.
+struct Stage {
+    argv: Vec<String>,
+    stdin: Option<String>,
+    stdout: Option<String>,
+    append: bool,
+    stderr: Option<String>,
+}
+
+/// Split `input` on `|` into pipeline stages, pulling `<file`, `>file`, `>>file`
+/// and `2>file` redirections out of each stage's token list.
+fn parse_pipeline(input: &str) -> Vec<Stage> {
+    input.split('|').map(parse_stage).collect()
+}
+
+fn parse_stage(seg: &str) -> Stage {
+    let tokens: Vec<&str> = seg.split_whitespace().collect();
+    let mut stage = Stage {
+        argv: Vec::new(),
+        stdin: None,
+        stdout: None,
+        append: false,
+        stderr: None,
+    };
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let tok = tokens[i];
+        if let Some(rest) = tok.strip_prefix(">>") {
+            stage.append = true;
+            stage.stdout = Some(take_redir_target(rest, &tokens, &mut i));
+        } else if let Some(rest) = tok.strip_prefix("2>") {
+            stage.stderr = Some(take_redir_target(rest, &tokens, &mut i));
+        } else if let Some(rest) = tok.strip_prefix('>') {
+            stage.stdout = Some(take_redir_target(rest, &tokens, &mut i));
+        } else if let Some(rest) = tok.strip_prefix('<') {
+            stage.stdin = Some(take_redir_target(rest, &tokens, &mut i));
+        } else {
+            stage.argv.push(tok.to_string());
+        }
+        i += 1;
+    }
+
+    stage
+}
+
+/// `rest` is whatever followed the redirection operator on the same token
+/// (e.g. `file.txt` in `>file.txt`). If it's empty, the filename is the next
+/// token instead (e.g. `>` `file.txt`), and `i` is advanced past it.
+fn take_redir_target(rest: &str, tokens: &[&str], i: &mut usize) -> String {
+    if !rest.is_empty() {
+        return rest.to_string();
+    }
+    *i += 1;
+    tokens.get(*i).map(|s| s.to_string()).unwrap_or_default()
+}
+
+fn has_glob_chars(s: &str) -> bool {
+    s.chars().any(|c| matches!(c, '*' | '?' | '['))
+}
+
+/// Match a single path segment (no `/`) against a glob pattern: `*` matches
+/// any run of chars, `?` matches one, and `[abc]`/`[a-z]`/`[!abc]` matches a
+/// character class.
+fn glob_match_segment(pattern: &str, name: &str) -> bool {
+    fn match_here(p: &[char], n: &[char]) -> bool {
+        if p.is_empty() {
+            return n.is_empty();
+        }
+        match p[0] {
+            '*' => (0..=n.len()).any(|i| match_here(&p[1..], &n[i..])),
+            '?' => !n.is_empty() && match_here(&p[1..], &n[1..]),
+            '[' => {
+                if n.is_empty() {
+                    return false;
+                }
+                match p.iter().position(|&c| c == ']') {
+                    Some(close) if char_in_class(&p[1..close], n[0]) => {
+                        match_here(&p[close + 1..], &n[1..])
+                    }
+                    Some(_) => false,
+                    None => n[0] == '[' && match_here(&p[1..], &n[1..]),
+                }
+            }
+            c => !n.is_empty() && n[0] == c && match_here(&p[1..], &n[1..]),
+        }
+    }
+
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    match_here(&p, &n)
+}
+
+fn char_in_class(class: &[char], c: char) -> bool {
+    let negate = matches!(class.first(), Some('!') | Some('^'));
+    let class = if negate { &class[1..] } else { class };
+
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            if c >= class[i] && c <= class[i + 2] {
+                found = true;
+            }
+            i += 3;
+        } else {
+            if class[i] == c {
+                found = true;
+            }
+            i += 1;
+        }
+    }
+    found != negate
+}
+
+/// Recursively walk `dir` matching `segments` (a glob pattern split on `/`)
+/// segment by segment, collecting paths (relative to the initial `dir`,
+/// joined with `/`) that match all of them. Dotfiles are only matched when
+/// the corresponding segment itself starts with `.`, per POSIX glob rules.
+fn glob_walk(dir: &Path, prefix: &str, segments: &[&str]) -> Vec<String> {
+    if segments.is_empty() {
+        return vec![prefix.to_string()];
+    }
+
+    let seg = segments[0];
+    let rest = &segments[1..];
+    let mut out = Vec::new();
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return out;
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect();
+    names.sort();
+
+    for name in names {
+        if !seg.starts_with('.') && name.starts_with('.') {
+            continue;
+        }
+        if !glob_match_segment(seg, &name) {
+            continue;
+        }
+
+        let child_path = dir.join(&name);
+        let child_prefix = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        if rest.is_empty() {
+            out.push(child_prefix);
+        } else if child_path.is_dir() {
+            out.extend(glob_walk(&child_path, &child_prefix, rest));
+        }
+    }
+
+    out
+}
+
+/// Expand a glob token against the filesystem into a sorted list of matching
+/// paths. Returns an empty `Vec` (not the literal token) when nothing
+/// matches; the caller falls back to the literal token per POSIX behavior.
+fn expand_glob(token: &str) -> Vec<String> {
+    let (dir, rest, prefix) = match token.strip_prefix('/') {
+        Some(stripped) => (PathBuf::from("/"), stripped, "/".to_string()),
+        None => (PathBuf::from("."), token, String::new()),
+    };
+
+    let segments: Vec<&str> = rest.split('/').collect();
+    let mut matches = glob_walk(&dir, "", &segments);
+    matches.sort();
+    matches.into_iter().map(|m| format!("{}{}", prefix, m)).collect()
+}
+
 struct Sh {
     cfg: Cfg,
     cfg_path: String,
     ed: Editor<Comp>,
+    last_status: i32,
 }
 
 impl Sh {
@@ -108,7 +496,7 @@ impl Sh {
         let home = env::var("HOME").unwrap_or_else(|_| "./".to_string());
         let cfg_path = format!("{}/.sh_cfg", home);
 
-        let cfg = if Path::new(&cfg_path).exists() {
+        let mut cfg: Cfg = if Path::new(&cfg_path).exists() {
             fs::read_to_string(&cfg_path)
                 .ok()
                 .and_then(|c| toml::from_str(&c).ok())
@@ -117,11 +505,74 @@ impl Sh {
             Cfg::default()
         };
 
+        // `cfg.env` is loaded verbatim from the previous session's config, so
+        // without this a fresh shell started in a new directory would hand
+        // children a stale PWD until the first `cd`.
+        if let Ok(cwd) = env::current_dir() {
+            cfg.env.insert("PWD".to_string(), cwd.display().to_string());
+        }
+
+        let search_mode = Arc::new(AtomicBool::new(false));
+        let search_index = Arc::new(AtomicUsize::new(0usize));
+        let search_match = Arc::new(Mutex::new(None));
+
         let rl_cfg = RlCfg::builder().auto_add_history(true).build();
         let mut ed = Editor::with_config(rl_cfg);
-        ed.set_helper(Some(Comp));
+        ed.set_helper(Some(Comp {
+            search_mode: search_mode.clone(),
+            search_index: search_index.clone(),
+            search_match: search_match.clone(),
+        }));
 
-        Self { cfg, cfg_path, ed }
+        ed.bind_sequence(
+            KeyEvent::ctrl('R'),
+            EventHandler::Conditional(Box::new(ToggleSearch {
+                mode: search_mode.clone(),
+                index: search_index.clone(),
+            })),
+        );
+        ed.bind_sequence(
+            KeyEvent(KeyCode::Up, Modifiers::NONE),
+            EventHandler::Conditional(Box::new(CycleSearch {
+                mode: search_mode.clone(),
+                index: search_index.clone(),
+                up: true,
+            })),
+        );
+        ed.bind_sequence(
+            KeyEvent(KeyCode::Down, Modifiers::NONE),
+            EventHandler::Conditional(Box::new(CycleSearch {
+                mode: search_mode.clone(),
+                index: search_index.clone(),
+                up: false,
+            })),
+        );
+        ed.bind_sequence(
+            KeyEvent(KeyCode::Enter, Modifiers::NONE),
+            EventHandler::Conditional(Box::new(AcceptSearch {
+                mode: search_mode.clone(),
+                search_match: search_match.clone(),
+                keep_open: false,
+            })),
+        );
+        ed.bind_sequence(
+            KeyEvent(KeyCode::Tab, Modifiers::NONE),
+            EventHandler::Conditional(Box::new(AcceptSearch {
+                mode: search_mode.clone(),
+                search_match: search_match.clone(),
+                keep_open: true,
+            })),
+        );
+        ed.bind_sequence(
+            KeyEvent(KeyCode::Esc, Modifiers::NONE),
+            EventHandler::Conditional(Box::new(CancelSearch { mode: search_mode.clone() })),
+        );
+        ed.bind_sequence(
+            KeyEvent(KeyCode::Right, Modifiers::NONE),
+            EventHandler::Conditional(Box::new(AcceptHint { search_mode: search_mode.clone() })),
+        );
+
+        Self { cfg, cfg_path, ed, last_status: 0 }
     }
 
     fn save(&self) {
@@ -137,13 +588,19 @@ impl Sh {
             let user = whoami::username();
             let cwd = env::current_dir().unwrap_or_else(|_| Path::new("/").to_path_buf());
             let time = Local::now().format("%H:%M:%S");
+            let status = if self.last_status == 0 {
+                format!("\x1b[1;32m{}\x1b[0m", self.last_status)
+            } else {
+                format!("\x1b[1;31m{}\x1b[0m", self.last_status)
+            };
+            let prompt = self.cfg.ps.replace("{status}", &status);
             let ps = format!(
                 "\x1b[1;32m{}@{}\x1b[0m \x1b[1;34m{}\x1b[0m [{}] {} ",
                 user,
                 whoami::hostname(),
                 cwd.display(),
                 time,
-                self.cfg.ps
+                prompt
             );
 
             match self.ed.readline(&ps) {
@@ -175,8 +632,165 @@ impl Sh {
         path.to_string()
     }
 
+    /// Expand a redirection target the same way an argument token is: `$VAR`s,
+    /// then `~`, then a glob. A redirect target names a single file, so an
+    /// ambiguous glob just takes the first match instead of fanning out.
+    fn expand_redir_target(&self, s: &str) -> String {
+        let expanded = self.expand_path(&self.expand_vars_in(s));
+        if !has_glob_chars(&expanded) {
+            return expanded;
+        }
+        expand_glob(&expanded).into_iter().next().unwrap_or(expanded)
+    }
+
+    /// Tilde-expand every argument, then glob-expand any that contain `*`,
+    /// `?`, or `[`, leaving the literal token in place when the glob matches
+    /// nothing. There is no escape syntax: a backslash before one of these
+    /// characters does not stop the expansion.
+    fn expand_args(&self, args: Vec<String>) -> Vec<String> {
+        args.into_iter()
+            .flat_map(|tok| {
+                let expanded = self.expand_path(&tok);
+                if !has_glob_chars(&expanded) {
+                    return vec![expanded];
+                }
+                let matches = expand_glob(&expanded);
+                if matches.is_empty() {
+                    vec![expanded]
+                } else {
+                    matches
+                }
+            })
+            .collect()
+    }
+
+    /// Look up a shell variable: `?` and `status` always give the last exit
+    /// status; any other name is looked up in the stored `env` map first,
+    /// then the process environment.
+    fn lookup_var(&self, name: &str) -> String {
+        if name == "?" || name == "status" {
+            return self.last_status.to_string();
+        }
+        self.cfg
+            .env
+            .get(name)
+            .cloned()
+            .or_else(|| env::var(name).ok())
+            .unwrap_or_default()
+    }
+
+    /// Replace `$NAME` and `${NAME}` occurrences in `s` with their value.
+    fn expand_vars_in(&self, s: &str) -> String {
+        let chars: Vec<char> = s.chars().collect();
+        let mut out = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] == '$' && i + 1 < chars.len() {
+                if chars[i + 1] == '{' {
+                    if let Some(end) = chars[i + 2..].iter().position(|&c| c == '}') {
+                        let name: String = chars[i + 2..i + 2 + end].iter().collect();
+                        out.push_str(&self.lookup_var(&name));
+                        i += 2 + end + 1;
+                        continue;
+                    }
+                } else if chars[i + 1] == '?' {
+                    out.push_str(&self.lookup_var("?"));
+                    i += 2;
+                    continue;
+                } else if chars[i + 1].is_alphanumeric() || chars[i + 1] == '_' {
+                    let mut j = i + 1;
+                    while j < chars.len() && (chars[j].is_alphanumeric() || chars[j] == '_') {
+                        j += 1;
+                    }
+                    let name: String = chars[i + 1..j].iter().collect();
+                    out.push_str(&self.lookup_var(&name));
+                    i = j;
+                    continue;
+                }
+            }
+            out.push(chars[i]);
+            i += 1;
+        }
+        out
+    }
+
+    /// Recursively resolve `cmd` through `cfg.al`, prepending each alias's own
+    /// args ahead of `user_args` (cargo's `aliased_command` style), stopping
+    /// once the head token is no longer an alias. Errors out on a cycle.
+    fn resolve_alias(&self, cmd: &str, user_args: &[String]) -> Result<(String, Vec<String>), String> {
+        let mut seen = HashSet::new();
+        let mut head = cmd.to_string();
+        let mut prefix_args: Vec<String> = Vec::new();
+
+        while let Some(value) = self.cfg.al.get(&head).cloned() {
+            if !seen.insert(head.clone()) {
+                return Err(format!("alias loop detected: {}", head));
+            }
+
+            let mut tokens = value.split_whitespace().map(|s| s.to_string());
+            let new_head = match tokens.next() {
+                Some(h) => h,
+                None => break,
+            };
+            let rest: Vec<String> = tokens.collect();
+            prefix_args = rest.into_iter().chain(prefix_args).collect();
+
+            // A self-referential alias (`alias ls="ls -la"`, the standard
+            // idiom for tacking on default flags) would otherwise loop
+            // forever looking itself up; real shells don't re-expand an
+            // alias within its own replacement text, so stop here instead.
+            if new_head == head {
+                head = new_head;
+                break;
+            }
+            head = new_head;
+        }
+
+        let mut args = prefix_args;
+        args.extend(user_args.iter().cloned());
+        Ok((head, args))
+    }
+
     fn handle(&mut self, input: String) {
-        let mut parts = input.trim().split_whitespace();
+        // Sniff for pipe/redirect syntax and split on it *before* expanding
+        // `$VAR`s, and expand each resulting token on its own. Expanding the
+        // raw line first would let a variable's value (e.g. a `MSG` holding
+        // `>pwned`) get reinterpreted as shell syntax.
+        let trimmed = input.trim();
+        if trimmed.contains('|') || trimmed.contains('>') || trimmed.contains('<') {
+            let mut stages = parse_pipeline(trimmed);
+            for stage in stages.iter_mut() {
+                stage.argv = stage.argv.iter().map(|tok| self.expand_vars_in(tok)).collect();
+                stage.stdin = stage.stdin.as_ref().map(|p| self.expand_redir_target(p));
+                stage.stdout = stage.stdout.as_ref().map(|p| self.expand_redir_target(p));
+                stage.stderr = stage.stderr.as_ref().map(|p| self.expand_redir_target(p));
+            }
+            for stage in stages.iter_mut() {
+                if let Some(cmd) = stage.argv.first().cloned() {
+                    let user_args = stage.argv[1..].to_vec();
+                    match self.resolve_alias(&cmd, &user_args) {
+                        Ok((resolved_cmd, args)) => {
+                            stage.argv = std::iter::once(resolved_cmd).chain(args).collect();
+                        }
+                        Err(e) => {
+                            println!("{}", e);
+                            return;
+                        }
+                    }
+                }
+            }
+            for stage in stages.iter_mut() {
+                if stage.argv.len() > 1 {
+                    let rest = stage.argv.split_off(1);
+                    stage.argv.extend(self.expand_args(rest));
+                }
+            }
+            self.exec_pipeline(&stages);
+            return;
+        }
+
+        let words: Vec<String> = trimmed.split_whitespace().map(|w| self.expand_vars_in(w)).collect();
+        let mut parts = words.iter().map(|s| s.as_str());
         if let Some(cmd) = parts.next() {
             match cmd {
                 "exit" => {
@@ -193,20 +807,57 @@ impl Sh {
                     }
                 }
                 "env" => {
-                    for (k, v) in env::vars() {
+                    let mut merged: HashMap<String, String> = env::vars().collect();
+                    merged.extend(self.cfg.env.clone());
+                    for (k, v) in merged {
                         println!("{}={}", k, v);
                     }
                 }
                 "cd" => {
                     if let Some(dir) = parts.next() {
-                        let expanded = self.expand_path(dir);
-                        if let Err(e) = env::set_current_dir(expanded) {
-                            println!("Erro: {}", e);
+                        let target = if dir == "-" {
+                            match self.cfg.env.get("OLDPWD").cloned() {
+                                Some(p) => p,
+                                None => {
+                                    println!("OLDPWD não definido");
+                                    return;
+                                }
+                            }
+                        } else {
+                            self.expand_path(dir)
+                        };
+
+                        let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+                        match env::set_current_dir(&target) {
+                            Ok(()) => {
+                                let new_cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from(&target));
+                                if dir == "-" {
+                                    println!("{}", new_cwd.display());
+                                }
+                                self.cfg.env.insert("OLDPWD".to_string(), cwd.display().to_string());
+                                self.cfg.env.insert("PWD".to_string(), new_cwd.display().to_string());
+                            }
+                            Err(e) => println!("Erro: {}", e),
                         }
                     } else {
                         println!("Uso: cd <diretório>");
                     }
                 }
+                "export" | "set" => {
+                    if let Some((k, v)) = parts.collect::<Vec<&str>>().join(" ").split_once('=') {
+                        self.cfg.env.insert(k.to_string(), v.to_string());
+                        println!("{}: {} -> {}", cmd, k, v);
+                    } else {
+                        println!("Uso: {} NOME=valor", cmd);
+                    }
+                }
+                "unset" => {
+                    if let Some(name) = parts.next() {
+                        self.cfg.env.remove(name);
+                    } else {
+                        println!("Uso: unset NOME");
+                    }
+                }
                 "source" => {
                     if let Some(file) = parts.next() {
                         let expanded = self.expand_path(file);
@@ -222,30 +873,157 @@ impl Sh {
                     }
                 }
                 _ => {
-                    let cmd = self.cfg.al.get(cmd).cloned().unwrap_or_else(|| cmd.to_string());
-                    let args: Vec<&str> = parts.collect();
-                    self.exec(&cmd, &args);
+                    let user_args: Vec<String> = parts.map(|s| s.to_string()).collect();
+                    match self.resolve_alias(cmd, &user_args) {
+                        Ok((resolved_cmd, args)) => {
+                            let args = self.expand_args(args);
+                            let arg_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+                            self.exec(&resolved_cmd, &arg_refs);
+                        }
+                        Err(e) => println!("{}", e),
+                    }
                 }
             }
         }
     }
 
-    fn exec(&self, cmd: &str, args: &[&str]) {
+    fn exec(&mut self, cmd: &str, args: &[&str]) {
         match Command::new(cmd)
             .args(args)
+            .envs(&self.cfg.env)
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
             .status()
         {
             Ok(status) => {
+                self.last_status = status.code().unwrap_or(-1);
                 if !status.success() {
                     println!("Falhou: {:?}", status);
                 }
             }
-            Err(e) => println!("Erro ao executar: {:?}", e),
+            Err(e) => {
+                self.last_status = 127;
+                println!("Erro ao executar: {:?}", e);
+            }
+        }
+    }
+
+    /// Run a pipeline of stages, wiring each stage's stdout into the next
+    /// stage's stdin (or a file, if redirected). Stores the last stage's
+    /// exit code in `self.last_status`.
+    fn exec_pipeline(&mut self, stages: &[Stage]) {
+        let n = stages.len();
+        let mut children = Vec::new();
+        let mut prev_stdout: Option<std::process::ChildStdout> = None;
+
+        for (idx, stage) in stages.iter().enumerate() {
+            if stage.argv.is_empty() {
+                continue;
+            }
+
+            let mut command = Command::new(&stage.argv[0]);
+            command.args(&stage.argv[1..]);
+            command.envs(&self.cfg.env);
+
+            if let Some(path) = &stage.stdin {
+                match fs::File::open(path) {
+                    Ok(f) => {
+                        command.stdin(Stdio::from(f));
+                    }
+                    Err(e) => {
+                        println!("Erro ao abrir {}: {}", path, e);
+                        self.last_status = -1;
+                        drop(prev_stdout);
+                        reap(children);
+                        return;
+                    }
+                }
+            } else if let Some(stdout) = prev_stdout.take() {
+                command.stdin(Stdio::from(stdout));
+            } else if idx == 0 {
+                command.stdin(Stdio::inherit());
+            }
+
+            let is_last = idx == n - 1;
+            if let Some(path) = &stage.stdout {
+                let file = if stage.append {
+                    fs::OpenOptions::new().create(true).append(true).open(path)
+                } else {
+                    fs::File::create(path)
+                };
+                match file {
+                    Ok(f) => {
+                        command.stdout(Stdio::from(f));
+                    }
+                    Err(e) => {
+                        println!("Erro ao abrir {}: {}", path, e);
+                        self.last_status = -1;
+                        drop(prev_stdout);
+                        drop(command);
+                        reap(children);
+                        return;
+                    }
+                }
+            } else if is_last {
+                command.stdout(Stdio::inherit());
+            } else {
+                command.stdout(Stdio::piped());
+            }
+
+            if let Some(path) = &stage.stderr {
+                match fs::File::create(path) {
+                    Ok(f) => {
+                        command.stderr(Stdio::from(f));
+                    }
+                    Err(e) => {
+                        println!("Erro ao abrir {}: {}", path, e);
+                        self.last_status = -1;
+                        drop(prev_stdout);
+                        drop(command);
+                        reap(children);
+                        return;
+                    }
+                }
+            } else {
+                command.stderr(Stdio::inherit());
+            }
+
+            match command.spawn() {
+                Ok(mut child) => {
+                    prev_stdout = child.stdout.take();
+                    children.push(child);
+                }
+                Err(e) => {
+                    println!("Erro ao executar {}: {:?}", stage.argv[0], e);
+                    self.last_status = -1;
+                    drop(prev_stdout);
+                    drop(command);
+                    reap(children);
+                    return;
+                }
+            }
+        }
+
+        // Close the shell's own end of any unconsumed pipe so an earlier
+        // stage gets EOF/SIGPIPE instead of blocking `reap` forever.
+        drop(prev_stdout);
+        self.last_status = reap(children).unwrap_or(-1);
+    }
+}
+
+/// Wait on every already-spawned stage so a later stage's spawn/redirection
+/// failure doesn't leave the earlier ones running as zombies until the shell
+/// exits. Returns the last child's exit code, if any.
+fn reap(children: Vec<std::process::Child>) -> Option<i32> {
+    let mut last = None;
+    for mut child in children {
+        match child.wait() {
+            Ok(status) => last = Some(status.code().unwrap_or(-1)),
+            Err(e) => println!("Erro: {:?}", e),
         }
     }
+    last
 }
 
 fn main() {